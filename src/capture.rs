@@ -0,0 +1,180 @@
+//! Audio capture sources.
+//!
+//! Both capture loops push fixed-size chunks of little-endian f32 PCM
+//! bytes into the same `SyncSender<Vec<u8>>` channel, so `main` doesn't
+//! care which one is running.
+
+use crate::realtime;
+use crate::Res;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::collections::VecDeque;
+use std::sync::mpsc::SyncSender;
+use std::sync::{Arc, Condvar, Mutex};
+use tracing::{debug, error, trace};
+#[cfg(windows)]
+use wasapi::*;
+
+/// Where to pull audio from on the sending side.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CaptureSource {
+    /// Portable microphone/line-in capture via cpal. Works on any platform.
+    CpalInput,
+    /// Windows-only WASAPI loopback capture of the default render (speaker) device.
+    WasapiLoopback,
+}
+
+/// Capture loop, capture samples and send in chunks of "chunksize" frames to channel.
+#[cfg(windows)]
+pub fn capture_loop_wasapi(
+    tx_capt: SyncSender<Vec<u8>>,
+    chunksize: usize,
+    sample_rate: u32,
+    realtime: bool,
+) -> Res<()> {
+    let _rt_guard = realtime.then(|| realtime::enable("capture"));
+
+    // Use `Direction::Capture` for normal capture,
+    // or `Direction::Render` for loopback mode (for capturing from a playback device).
+    let device = get_default_device(&Direction::Render)?;
+
+    let mut audio_client = device.get_iaudioclient()?;
+
+    let desired_format = WaveFormat::new(32, 32, &SampleType::Float, sample_rate as usize, 2, None);
+
+    let blockalign = desired_format.get_blockalign();
+    debug!("Desired capture format: {:?}", desired_format);
+
+    let (def_time, min_time) = audio_client.get_periods()?;
+    debug!("default period {}, min period {}", def_time, min_time);
+
+    audio_client.initialize_client(
+        &desired_format,
+        min_time,
+        &Direction::Capture,
+        &ShareMode::Shared,
+        true,
+    )?;
+    debug!("initialized capture");
+
+    let h_event = audio_client.set_get_eventhandle()?;
+
+    let buffer_frame_count = audio_client.get_bufferframecount()?;
+
+    let render_client = audio_client.get_audiocaptureclient()?;
+    let mut sample_queue: VecDeque<u8> = VecDeque::with_capacity(
+        100 * blockalign as usize * (1024 + 2 * buffer_frame_count as usize),
+    );
+    let session_control = audio_client.get_audiosessioncontrol()?;
+
+    debug!("state before start: {:?}", session_control.get_state());
+    audio_client.start_stream()?;
+    debug!("state after start: {:?}", session_control.get_state());
+
+    loop {
+        while sample_queue.len() > (blockalign as usize * chunksize) {
+            debug!("pushing samples");
+            let mut chunk = vec![0u8; blockalign as usize * chunksize];
+            for element in chunk.iter_mut() {
+                *element = sample_queue.pop_front().unwrap();
+            }
+            tx_capt.send(chunk)?;
+        }
+        trace!("capturing");
+        render_client.read_from_device_to_deque(&mut sample_queue)?;
+        if h_event.wait_for_event(3000).is_err() {
+            error!("timeout error, stopping capture");
+            audio_client.stop_stream()?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Find a config the input device actually supports for `channels`/
+/// `sample_rate` as f32, rather than assuming the device can produce
+/// whatever the codec/wire format wants. A mono mic asked for 2ch, or any
+/// device that doesn't have `sample_rate` in range, would otherwise make
+/// `build_input_stream` fail and leave the capture thread silently doing
+/// nothing.
+fn negotiate_input_config(
+    device: &cpal::Device,
+    channels: u16,
+    sample_rate: u32,
+) -> Res<cpal::StreamConfig> {
+    let desired_rate = cpal::SampleRate(sample_rate);
+    let supported = device.supported_input_configs()?;
+    let matched = supported
+        .filter(|range| range.channels() == channels && range.sample_format() == cpal::SampleFormat::F32)
+        .find(|range| range.min_sample_rate() <= desired_rate && desired_rate <= range.max_sample_rate());
+
+    match matched {
+        Some(range) => Ok(range.with_sample_rate(desired_rate).config()),
+        None => Err(format!(
+            "input device does not support {} channel(s) of f32 PCM at {} Hz",
+            channels, sample_rate
+        )
+        .into()),
+    }
+}
+
+/// Portable capture loop built on cpal's input stream. The data callback
+/// just appends bytes to a shared queue and wakes this function, which
+/// drains exact `chunksize`-frame chunks off it and forwards them to
+/// `tx_capt` - keeping the same fixed-size-chunk contract the WASAPI loop
+/// provides, which Opus framing and the network layer both depend on.
+pub fn capture_loop_cpal(
+    tx_capt: SyncSender<Vec<u8>>,
+    chunksize: usize,
+    sample_rate: u32,
+    channels: u16,
+    realtime: bool,
+) -> Res<()> {
+    let host = cpal::default_host();
+    let device = host
+        .default_input_device()
+        .ok_or("no input device available")?;
+    debug!("Using input device: {:?}", device.name());
+
+    let config = negotiate_input_config(&device, channels, sample_rate)?;
+    let blockalign = channels as usize * 4;
+    let chunk_bytes = blockalign * chunksize;
+
+    let shared: Arc<(Mutex<VecDeque<u8>>, Condvar)> =
+        Arc::new((Mutex::new(VecDeque::new()), Condvar::new()));
+    let cb_shared = shared.clone();
+    // cpal runs this callback on its own internal audio thread, not the
+    // thread that called `build_input_stream`, so the realtime guard has
+    // to be raised from inside it. It's created once, on the first
+    // callback invocation, and held in the closure for the stream's
+    // lifetime so priority drops back to normal when the stream is torn
+    // down.
+    let mut rt_guard: Option<realtime::Guard> = None;
+    let stream = device.build_input_stream(
+        &config,
+        move |data: &[f32], _| {
+            if realtime && rt_guard.is_none() {
+                rt_guard = Some(realtime::enable("capture"));
+            }
+            let (lock, cvar) = &*cb_shared;
+            let mut queue = lock.lock().unwrap();
+            for sample in data {
+                queue.extend(sample.to_le_bytes());
+            }
+            cvar.notify_all();
+        },
+        |err| error!("input stream error: {}", err),
+        None,
+    )?;
+    stream.play()?;
+
+    loop {
+        let (lock, cvar) = &*shared;
+        let mut queue = lock.lock().unwrap();
+        while queue.len() < chunk_bytes {
+            queue = cvar.wait(queue).unwrap();
+        }
+        let chunk: Vec<u8> = queue.drain(..chunk_bytes).collect();
+        drop(queue);
+        tx_capt.send(chunk)?;
+    }
+}
@@ -0,0 +1,141 @@
+//! Optional Opus compression for the PCM stream.
+//!
+//! Opus only accepts fixed frame sizes (2.5-60ms); the sender is expected to
+//! hand each `Encoder::encode` call exactly `OPUS_FRAME_SAMPLES` frames of
+//! interleaved f32 PCM, which `main` guarantees by sizing the capture
+//! loops' chunk size to match.
+
+use crate::Res;
+use audiopus::coder::{Decoder as OpusDecoder, Encoder as OpusEncoder};
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+pub enum Codec {
+    /// Uncompressed 32-bit float PCM, sent as-is.
+    Raw,
+    /// Opus-compressed PCM.
+    Opus,
+}
+
+/// Opus frame length, in samples per channel, used for both ends of the
+/// link. 960 samples at 48kHz is a 20ms frame, a common low-latency choice.
+pub const OPUS_FRAME_SAMPLES: usize = 960;
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+/// Largest encoded packet we'll ever ask Opus to produce; comfortably above
+/// what a 20ms/48kHz stereo frame compresses to at any sane bitrate.
+const MAX_PACKET_BYTES: usize = 4000;
+
+pub struct Encoder {
+    inner: OpusEncoder,
+}
+
+impl Encoder {
+    pub fn new(channels: u16, bitrate_bps: i32) -> Res<Self> {
+        let opus_channels = channels_to_opus(channels)?;
+        let mut inner = OpusEncoder::new(SampleRate::Hz48000, opus_channels, Application::Audio)
+            .map_err(|err| format!("failed to create opus encoder: {err:?}"))?;
+        inner
+            .set_bitrate(Bitrate::BitsPerSecond(bitrate_bps))
+            .map_err(|err| format!("failed to set opus bitrate: {err:?}"))?;
+        Ok(Encoder { inner })
+    }
+
+    /// Encode one frame of little-endian f32 PCM bytes (exactly
+    /// `OPUS_FRAME_SAMPLES * channels` samples) into an Opus packet.
+    pub fn encode(&mut self, pcm_bytes: &[u8]) -> Res<Vec<u8>> {
+        let samples = bytes_to_f32(pcm_bytes);
+        let mut packet = vec![0u8; MAX_PACKET_BYTES];
+        let len = self
+            .inner
+            .encode_float(&samples, &mut packet)
+            .map_err(|err| format!("opus encode failed: {err:?}"))?;
+        packet.truncate(len);
+        Ok(packet)
+    }
+}
+
+pub struct Decoder {
+    inner: OpusDecoder,
+    channels: usize,
+}
+
+impl Decoder {
+    pub fn new(channels: u16) -> Res<Self> {
+        let opus_channels = channels_to_opus(channels)?;
+        let inner = OpusDecoder::new(SampleRate::Hz48000, opus_channels)
+            .map_err(|err| format!("failed to create opus decoder: {err:?}"))?;
+        Ok(Decoder {
+            inner,
+            channels: channels as usize,
+        })
+    }
+
+    /// Decode one Opus packet back into little-endian f32 PCM bytes.
+    pub fn decode(&mut self, packet: &[u8]) -> Res<Vec<u8>> {
+        let mut samples = vec![0f32; OPUS_FRAME_SAMPLES * self.channels];
+        let decoded = self
+            .inner
+            .decode_float(Some(packet), &mut samples, false)
+            .map_err(|err| format!("opus decode failed: {err:?}"))?;
+        samples.truncate(decoded * self.channels);
+        Ok(f32_to_bytes(&samples))
+    }
+}
+
+fn channels_to_opus(channels: u16) -> Res<Channels> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        other => Err(format!("opus only supports mono or stereo, got {other} channels").into()),
+    }
+}
+
+fn bytes_to_f32(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn f32_to_bytes(samples: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(samples.len() * 4);
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip_preserves_frame_size() {
+        let channels = 2u16;
+        let mut encoder = Encoder::new(channels, 64_000).unwrap();
+        let mut decoder = Decoder::new(channels).unwrap();
+
+        // A simple sine-ish frame; Opus is lossy so we only assert on
+        // shape, not sample-exact equality.
+        let samples: Vec<f32> = (0..OPUS_FRAME_SAMPLES * channels as usize)
+            .map(|i| ((i as f32) * 0.01).sin() * 0.5)
+            .collect();
+        let pcm_bytes = f32_to_bytes(&samples);
+
+        let packet = encoder.encode(&pcm_bytes).unwrap();
+        assert!(!packet.is_empty());
+
+        let decoded_bytes = decoder.decode(&packet).unwrap();
+        let decoded_samples = bytes_to_f32(&decoded_bytes);
+        assert_eq!(decoded_samples.len(), OPUS_FRAME_SAMPLES * channels as usize);
+    }
+
+    #[test]
+    fn channels_to_opus_rejects_unsupported_channel_counts() {
+        assert!(channels_to_opus(1).is_ok());
+        assert!(channels_to_opus(2).is_ok());
+        assert!(channels_to_opus(6).is_err());
+    }
+}
@@ -1,17 +1,37 @@
+mod capture;
+mod codec;
+mod playback;
+mod protocol;
+mod realtime;
+mod transport;
+mod wav;
+
 use clap::Parser;
-use cpal::traits::{DeviceTrait, HostTrait};
-use rodio::OutputStream;
-use std::fs::File;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use std::io::prelude::*;
+use std::error;
+use std::net::TcpListener;
 use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::{collections::VecDeque, net::TcpListener};
-use std::{error, io};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info};
 use tracing_subscriber::EnvFilter;
 #[cfg(windows)]
 use wasapi::*;
 
+use capture::CaptureSource;
+use codec::Codec;
+use playback::PcmBuffer;
+
+/// Number of silent chunks queued before playback starts, to absorb network jitter.
+const PRIME_CHUNKS: usize = 4;
+/// Length, in samples, of each primed silence chunk.
+const PRIME_CHUNK_LEN: usize = 1024;
+/// Hardcoded capture/playback channel count.
+const CHANNELS: u16 = 2;
+/// Raw mode still runs at the original sample rate; Opus framing below targets 48kHz.
+const RAW_SAMPLE_RATE: u32 = 44100;
+
 #[derive(clap::Parser)]
 struct Args {
     #[arg(short, long)]
@@ -20,69 +40,31 @@ struct Args {
     port: u16,
     #[arg(short, long)]
     mode: bool,
+    /// Wire codec: "raw" for lossless uncompressed PCM, "opus" for compressed.
+    #[arg(short, long, value_enum, default_value = "raw")]
+    codec: Codec,
+    /// Opus target bitrate in bits/second. Ignored in raw mode.
+    #[arg(long, default_value_t = 64_000)]
+    bitrate: i32,
+    /// Shared key enabling a lightweight XOR stream cipher on the wire.
+    /// Must match between client and server. Omit for a plaintext stream.
+    #[arg(long)]
+    key: Option<String>,
+    /// Where to capture audio from in client mode.
+    #[arg(long, value_enum, default_value = "cpal-input")]
+    capture_source: CaptureSource,
+    /// In server mode, play this local WAV file instead of listening for a
+    /// network source. Useful for exercising the playback path standalone.
+    #[arg(long)]
+    play_wav: Option<String>,
+    /// Raise the capture and playback threads to real-time scheduling
+    /// priority. Falls back to default priority with a warning if the OS
+    /// refuses (e.g. missing permission).
+    #[arg(long)]
+    realtime: bool,
 }
 type Res<T> = Result<T, Box<dyn error::Error>>;
 
-// Capture loop, capture samples and send in chunks of "chunksize" frames to channel
-#[cfg(windows)]
-fn capture_loop(tx_capt: std::sync::mpsc::SyncSender<Vec<u8>>, chunksize: usize) -> Res<()> {
-    // Use `Direction::Capture` for normal capture,
-    // or `Direction::Render` for loopback mode (for capturing from a playback device).
-    let device = get_default_device(&Direction::Render)?;
-
-    let mut audio_client = device.get_iaudioclient()?;
-
-    let desired_format = WaveFormat::new(32, 32, &SampleType::Float, 44100, 2, None);
-
-    let blockalign = desired_format.get_blockalign();
-    debug!("Desired capture format: {:?}", desired_format);
-
-    let (def_time, min_time) = audio_client.get_periods()?;
-    debug!("default period {}, min period {}", def_time, min_time);
-
-    audio_client.initialize_client(
-        &desired_format,
-        min_time,
-        &Direction::Capture,
-        &ShareMode::Shared,
-        true,
-    )?;
-    debug!("initialized capture");
-
-    let h_event = audio_client.set_get_eventhandle()?;
-
-    let buffer_frame_count = audio_client.get_bufferframecount()?;
-
-    let render_client = audio_client.get_audiocaptureclient()?;
-    let mut sample_queue: VecDeque<u8> = VecDeque::with_capacity(
-        100 * blockalign as usize * (1024 + 2 * buffer_frame_count as usize),
-    );
-    let session_control = audio_client.get_audiosessioncontrol()?;
-
-    debug!("state before start: {:?}", session_control.get_state());
-    audio_client.start_stream()?;
-    debug!("state after start: {:?}", session_control.get_state());
-
-    loop {
-        while sample_queue.len() > (blockalign as usize * chunksize) {
-            debug!("pushing samples");
-            let mut chunk = vec![0u8; blockalign as usize * chunksize];
-            for element in chunk.iter_mut() {
-                *element = sample_queue.pop_front().unwrap();
-            }
-            tx_capt.send(chunk)?;
-        }
-        trace!("capturing");
-        render_client.read_from_device_to_deque(&mut sample_queue)?;
-        if h_event.wait_for_event(3000).is_err() {
-            error!("timeout error, stopping capture");
-            audio_client.stop_stream()?;
-            break;
-        }
-    }
-    Ok(())
-}
-
 // Main loop
 fn main() -> Res<()> {
     tracing_subscriber::fmt()
@@ -93,76 +75,278 @@ fn main() -> Res<()> {
 
     if args.mode {
         #[cfg(windows)]
-        {
+        if args.capture_source == CaptureSource::WasapiLoopback {
             initialize_mta().ok()?;
+        }
 
-            let (tx_capt, rx_capt): (
-                std::sync::mpsc::SyncSender<Vec<u8>>,
-                std::sync::mpsc::Receiver<Vec<u8>>,
-            ) = mpsc::sync_channel(2);
-            let chunksize = 4096;
+        let (tx_capt, rx_capt): (
+            std::sync::mpsc::SyncSender<Vec<u8>>,
+            std::sync::mpsc::Receiver<Vec<u8>>,
+        ) = mpsc::sync_channel(2);
+        // Opus needs fixed-size frames; sizing the capture chunk to exactly
+        // one Opus frame means every chunk off the channel is already on
+        // a frame boundary, no extra regrouping needed downstream.
+        let (sample_rate, chunksize) = match args.codec {
+            Codec::Raw => (RAW_SAMPLE_RATE, 4096),
+            Codec::Opus => (codec::OPUS_SAMPLE_RATE, codec::OPUS_FRAME_SAMPLES),
+        };
+        let capture_source = args.capture_source;
+        let realtime = args.realtime;
 
-            // Capture
-            let _handle = thread::Builder::new()
-                .name("Capture".to_string())
-                .spawn(move || {
-                    let result = capture_loop(tx_capt, chunksize);
-                    if let Err(err) = result {
-                        error!("Capture failed with error {}", err);
+        // Capture
+        let _handle = thread::Builder::new()
+            .name("Capture".to_string())
+            .spawn(move || {
+                let result = match capture_source {
+                    CaptureSource::CpalInput => capture::capture_loop_cpal(
+                        tx_capt, chunksize, sample_rate, CHANNELS, realtime,
+                    ),
+                    #[cfg(windows)]
+                    CaptureSource::WasapiLoopback => {
+                        capture::capture_loop_wasapi(tx_capt, chunksize, sample_rate, realtime)
+                    }
+                    #[cfg(not(windows))]
+                    CaptureSource::WasapiLoopback => {
+                        Err("WASAPI loopback capture is only available on Windows".into())
                     }
-                });
+                };
+                if let Err(err) = result {
+                    error!("Capture failed with error {}", err);
+                }
+            });
 
-            let mut outfile = File::create("recorded.raw")?;
+        let mut outfile = wav::WavWriter::create("recorded.wav", sample_rate, CHANNELS)?;
+        let mut encoder = match args.codec {
+            Codec::Raw => None,
+            Codec::Opus => Some(codec::Encoder::new(CHANNELS, args.bitrate)?),
+        };
 
         let addr = format!("{}:{}", args.address, args.port);
         let res = std::net::TcpStream::connect(addr);
-        let Ok(mut stream) = res else {
+        let Ok(stream) = res else {
             error!("Could not connect to server");
             return Ok(());
         };
+        let mut writer = match &args.key {
+            Some(key) => transport::Writer::encrypted(stream, key.as_bytes().to_vec()),
+            None => transport::Writer::plain(stream),
+        };
+
+        let header = protocol::StreamHeader {
+            sample_rate,
+            channels: CHANNELS,
+            sample_format: protocol::SampleFormat::F32,
+            codec: args.codec,
+        };
+        protocol::write_header(&mut writer, &header)?;
+
         loop {
             match rx_capt.recv() {
                 Ok(chunk) => {
                     debug!("writing to file");
-
-                    stream.write_all(&chunk)?;
                     outfile.write_all(&chunk)?;
+
+                    let wire_chunk = match &mut encoder {
+                        Some(encoder) => encoder.encode(&chunk)?,
+                        None => chunk,
+                    };
+                    protocol::write_frame(&mut writer, &wire_chunk)?;
                 }
                 Err(err) => {
                     error!("Some error {}", err);
                     return Ok(());
                 }
             }
-        };
-        Ok(())
+        }
     } else {
-        error!("Not implemented");
-        let server = TcpListener::bind(format!("{}:{}", args.address, args.port))?;
         let host = cpal::default_host();
         let device = host
             .default_output_device()
             .expect("no output device available");
-        let config = device.default_output_config().unwrap();
-        for stream in server.incoming() {
-            info!("New connection");
-            loop {
-                let n = stream.read(&mut buffer)?;
-                if n == 0 {
-                    info!("breakies");
-                    break;
+
+        if let Some(path) = &args.play_wav {
+            return play_wav_file(path, &device, args.realtime);
+        }
+
+        let server = TcpListener::bind(format!("{}:{}", args.address, args.port))?;
+        for conn in server.incoming() {
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(err) => {
+                    error!("Failed to accept connection: {}", err);
+                    continue;
                 }
-                let data = buffer[..n].to_vec();
-                println!("Received data: {:?}", data);
-                device.build_output_stream(
-                    &config.config(),
-                    move |data, _| {
-                        io::copy(&mut data, &mut stream).unwrap();
-                    },
-                    |err| {},
-                    None,
-                );
-            }
+            };
+            info!("New connection");
+            let device = device.clone();
+            let key = args.key.clone();
+            let realtime = args.realtime;
+            thread::Builder::new()
+                .name("Network".to_string())
+                .spawn(move || {
+                    if let Err(err) = handle_connection(conn, &device, key, realtime) {
+                        error!("Connection handler failed: {}", err);
+                    }
+                })?;
         }
         Ok(())
     }
 }
+
+/// Play a local WAV file through the same playback buffer and output
+/// stream a network source would use, so the playback path can be
+/// exercised without a live capture peer.
+fn play_wav_file(path: &str, device: &cpal::Device, realtime: bool) -> Res<()> {
+    let (format, data) = wav::read_wav_pcm(path)?;
+    if format.bits_per_sample != 32 {
+        return Err(format!(
+            "only 32-bit float WAV files are supported, got {} bits/sample",
+            format.bits_per_sample
+        )
+        .into());
+    }
+
+    let stream_config = cpal::StreamConfig {
+        channels: format.channels,
+        sample_rate: cpal::SampleRate(format.sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+
+    let shared_buf: playback::SharedPcmBuffer =
+        Arc::new((Mutex::new(PcmBuffer::new()), Condvar::new()));
+
+    let stream_buf = shared_buf.clone();
+    // cpal runs this callback on its own internal audio thread, so the
+    // realtime guard has to be raised from inside it rather than on the
+    // thread that builds the stream. Created once on the first callback
+    // and held in the closure for the stream's lifetime, so priority drops
+    // back to normal when the stream is torn down.
+    let mut rt_guard: Option<realtime::Guard> = None;
+    let output_stream = device.build_output_stream(
+        &stream_config,
+        move |data: &mut [f32], _| {
+            if realtime && rt_guard.is_none() {
+                rt_guard = Some(realtime::enable("playback"));
+            }
+            let (lock, _cvar) = &*stream_buf;
+            let mut buf = lock.lock().unwrap();
+            buf.consume_exact(data);
+        },
+        |err| error!("output stream error: {}", err),
+        None,
+    )?;
+
+    let sample_count = data.len() / 4 / format.channels as usize;
+    {
+        let (lock, cvar) = &*shared_buf;
+        let mut buf = lock.lock().unwrap();
+        buf.produce_bytes(&data);
+        buf.mark_primed();
+        cvar.notify_all();
+    }
+    output_stream.play()?;
+
+    let duration = std::time::Duration::from_secs_f64(sample_count as f64 / format.sample_rate as f64);
+    thread::sleep(duration);
+    Ok(())
+}
+
+/// Find a config the output device actually supports for the format the
+/// stream header describes. We don't carry a resampler, so a device that
+/// can't produce `header.sample_rate`/`header.channels` as f32 directly is
+/// reported as an error up front rather than letting `build_output_stream`
+/// fail deep inside `handle_connection` or silently play back at the wrong
+/// rate.
+fn negotiate_output_config(
+    device: &cpal::Device,
+    header: &protocol::StreamHeader,
+) -> Res<cpal::StreamConfig> {
+    let desired_rate = cpal::SampleRate(header.sample_rate);
+    let supported = device.supported_output_configs()?;
+    let matched = supported
+        .filter(|range| {
+            range.channels() == header.channels
+                && range.sample_format() == cpal::SampleFormat::F32
+        })
+        .find(|range| range.min_sample_rate() <= desired_rate && desired_rate <= range.max_sample_rate());
+
+    match matched {
+        Some(range) => Ok(range.with_sample_rate(desired_rate).config()),
+        None => Err(format!(
+            "output device does not support {} channel(s) of f32 PCM at {} Hz, and resampling \
+             is not implemented; pick a device/rate the stream's format matches",
+            header.channels, header.sample_rate
+        )
+        .into()),
+    }
+}
+
+/// Service one client connection end-to-end: read the format handshake,
+/// configure and start a cpal output stream to match it, then pull framed
+/// PCM/Opus chunks off the socket until the peer disconnects.
+fn handle_connection(
+    conn: std::net::TcpStream,
+    device: &cpal::Device,
+    key: Option<String>,
+    realtime: bool,
+) -> Res<()> {
+    let mut reader = match key {
+        Some(key) => transport::Reader::encrypted(conn, key.as_bytes().to_vec()),
+        None => transport::Reader::plain(conn),
+    };
+
+    let header = protocol::read_header(&mut reader)?;
+    info!("Stream header: {:?}", header);
+
+    let stream_config = negotiate_output_config(device, &header)?;
+
+    let shared_buf: playback::SharedPcmBuffer =
+        Arc::new((Mutex::new(PcmBuffer::new()), Condvar::new()));
+
+    let stream_buf = shared_buf.clone();
+    // cpal runs this callback on its own internal audio thread, so the
+    // realtime guard has to be raised from inside it rather than on the
+    // thread that builds the stream. Created once on the first callback
+    // and held in the closure for the stream's lifetime, so priority drops
+    // back to normal when the stream is torn down.
+    let mut rt_guard: Option<realtime::Guard> = None;
+    let output_stream = device.build_output_stream(
+        &stream_config,
+        move |data: &mut [f32], _| {
+            if realtime && rt_guard.is_none() {
+                rt_guard = Some(realtime::enable("playback"));
+            }
+            let (lock, _cvar) = &*stream_buf;
+            let mut buf = lock.lock().unwrap();
+            buf.consume_exact(data);
+        },
+        |err| error!("output stream error: {}", err),
+        None,
+    )?;
+
+    playback::prime_with_silence(&shared_buf, PRIME_CHUNKS, PRIME_CHUNK_LEN);
+    playback::wait_until_primed(&shared_buf);
+    output_stream.play()?;
+
+    let mut decoder = match header.codec {
+        Codec::Raw => None,
+        Codec::Opus => Some(codec::Decoder::new(header.channels)?),
+    };
+
+    loop {
+        let Some(chunk) = protocol::read_frame(&mut reader)? else {
+            info!("Connection closed");
+            return Ok(());
+        };
+
+        let pcm_bytes = match &mut decoder {
+            Some(decoder) => decoder.decode(&chunk)?,
+            None => chunk,
+        };
+
+        let (lock, cvar) = &*shared_buf;
+        let mut buf = lock.lock().unwrap();
+        buf.produce_bytes(&pcm_bytes);
+        cvar.notify_all();
+    }
+}
@@ -0,0 +1,196 @@
+//! Decoupled PCM playback buffer.
+//!
+//! Network I/O and audio-device I/O run on different clocks: the network
+//! thread receives chunks in bursts, while the cpal output callback demands
+//! samples on a tight, real-time schedule. `PcmBuffer` sits between them so
+//! the callback never has to wait on the network.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use tracing::warn;
+
+/// Upper bound on queued chunks. Past this depth the producer is outrunning
+/// the consumer (clock drift or a burst), so we drop the oldest queued
+/// chunk to catch back up instead of letting the buffer - and playback
+/// latency - grow without bound.
+const MAX_QUEUED_CHUNKS: usize = 32;
+
+/// Queue of decoded PCM chunks waiting to be played out.
+///
+/// `chunks` holds whole chunks as they arrive; `consumer_cursor` tracks how
+/// far into the front chunk the consumer has already read, so the consumer
+/// only pops a chunk once it's fully drained (the depth cap in
+/// `enforce_depth_cap` can still drop a partly read oldest chunk). Backed
+/// by a `VecDeque` so draining
+/// the front chunk on the realtime audio callback's hot path is O(1).
+pub struct PcmBuffer {
+    chunks: VecDeque<Vec<f32>>,
+    consumer_cursor: usize,
+    primed: bool,
+}
+
+/// Shared handle: the `Condvar` wakes anyone waiting for the buffer to be
+/// primed once the network thread has queued its initial run of silence.
+pub type SharedPcmBuffer = Arc<(Mutex<PcmBuffer>, Condvar)>;
+
+impl PcmBuffer {
+    pub fn new() -> Self {
+        PcmBuffer {
+            chunks: VecDeque::new(),
+            consumer_cursor: 0,
+            primed: false,
+        }
+    }
+
+    /// Drop the oldest queued chunk if we're past `MAX_QUEUED_CHUNKS`, so a
+    /// producer that's outrunning the consumer can't grow the buffer (and
+    /// playback latency) without bound.
+    fn enforce_depth_cap(&mut self) {
+        if self.chunks.len() >= MAX_QUEUED_CHUNKS {
+            self.chunks.pop_front();
+            self.consumer_cursor = 0;
+            warn!(
+                "playback buffer exceeded {} queued chunks, dropped oldest to catch up",
+                MAX_QUEUED_CHUNKS
+            );
+        }
+    }
+
+    /// Decode little-endian f32 samples out of a raw byte chunk and queue them.
+    pub fn produce_bytes(&mut self, bytes: &[u8]) {
+        let samples = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<Vec<f32>>();
+        self.enforce_depth_cap();
+        self.chunks.push_back(samples);
+    }
+
+    /// Queue `n` silent chunks of `chunk_len` samples, used to absorb jitter
+    /// before the network has delivered any real audio.
+    pub fn produce_silence(&mut self, n: usize, chunk_len: usize) {
+        for _ in 0..n {
+            self.enforce_depth_cap();
+            self.chunks.push_back(vec![0.0; chunk_len]);
+        }
+    }
+
+    /// Fill `out` with queued samples, advancing `consumer_cursor` and
+    /// popping exhausted chunks. Returns `false` (and pads with silence) if
+    /// fewer samples were queued than requested, so the caller never blocks.
+    pub fn consume_exact(&mut self, out: &mut [f32]) -> bool {
+        let mut filled = 0;
+        while filled < out.len() {
+            let Some(front) = self.chunks.front() else {
+                break;
+            };
+            let available = front.len() - self.consumer_cursor;
+            let need = out.len() - filled;
+            let take = available.min(need);
+            out[filled..filled + take]
+                .copy_from_slice(&front[self.consumer_cursor..self.consumer_cursor + take]);
+            filled += take;
+            self.consumer_cursor += take;
+            if self.consumer_cursor == front.len() {
+                self.chunks.pop_front();
+                self.consumer_cursor = 0;
+            }
+        }
+        if filled < out.len() {
+            for sample in &mut out[filled..] {
+                *sample = 0.0;
+            }
+            warn!("playback buffer underrun, {} samples short", out.len() - filled);
+            return false;
+        }
+        true
+    }
+
+    pub fn mark_primed(&mut self) {
+        self.primed = true;
+    }
+
+    pub fn is_primed(&self) -> bool {
+        self.primed
+    }
+}
+
+impl Default for PcmBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Queue `count` chunks of `chunk_len` samples of silence and mark the
+/// buffer primed, waking anyone waiting on it.
+pub fn prime_with_silence(shared: &SharedPcmBuffer, count: usize, chunk_len: usize) {
+    let (lock, cvar) = &**shared;
+    let mut buf = lock.lock().unwrap();
+    buf.produce_silence(count, chunk_len);
+    buf.mark_primed();
+    cvar.notify_all();
+}
+
+/// Block until the buffer has been primed (or the short jitter-absorbing
+/// run of silence has been queued).
+pub fn wait_until_primed(shared: &SharedPcmBuffer) {
+    let (lock, cvar) = &**shared;
+    let guard = lock.lock().unwrap();
+    let _guard = cvar.wait_while(guard, |buf| !buf.is_primed()).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes_of(samples: &[f32]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn consume_exact_spans_multiple_queued_chunks() {
+        let mut buf = PcmBuffer::new();
+        buf.produce_bytes(&bytes_of(&[1.0, 2.0, 3.0]));
+        buf.produce_bytes(&bytes_of(&[4.0, 5.0]));
+
+        let mut out = [0.0; 4];
+        assert!(buf.consume_exact(&mut out));
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+
+        let mut out = [0.0; 1];
+        assert!(buf.consume_exact(&mut out));
+        assert_eq!(out, [5.0]);
+    }
+
+    #[test]
+    fn consume_exact_pads_with_silence_on_underrun() {
+        let mut buf = PcmBuffer::new();
+        buf.produce_bytes(&bytes_of(&[1.0, 2.0]));
+
+        let mut out = [9.0; 4];
+        assert!(!buf.consume_exact(&mut out));
+        assert_eq!(out, [1.0, 2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn consume_exact_on_empty_buffer_is_all_silence() {
+        let mut buf = PcmBuffer::new();
+        let mut out = [9.0; 3];
+        assert!(!buf.consume_exact(&mut out));
+        assert_eq!(out, [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn produce_bytes_caps_queue_depth() {
+        let mut buf = PcmBuffer::new();
+        for i in 0..(MAX_QUEUED_CHUNKS + 5) {
+            buf.produce_bytes(&bytes_of(&[i as f32]));
+        }
+        assert_eq!(buf.chunks.len(), MAX_QUEUED_CHUNKS);
+        // The oldest chunks should have been dropped, leaving the most
+        // recent MAX_QUEUED_CHUNKS values at the front.
+        let mut out = [0.0; 1];
+        assert!(buf.consume_exact(&mut out));
+        assert_eq!(out, [5.0]);
+    }
+}
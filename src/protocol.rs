@@ -0,0 +1,67 @@
+//! Self-describing framing for the TCP stream.
+//!
+//! Every message on the wire — the header and each PCM/Opus chunk — is
+//! length-prefixed with a little-endian `u32`. The very first frame a
+//! client sends is a MessagePack-encoded [`StreamHeader`]; everything after
+//! that is audio data in the format the header describes.
+
+use crate::codec::Codec;
+use crate::Res;
+use serde::{Deserialize, Serialize};
+use std::io::{ErrorKind, Read, Write};
+
+/// Sample format carried on the wire. Only float is produced by this
+/// project today, but the field keeps the header honest about what's
+/// actually in each chunk rather than assuming agreement out-of-band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleFormat {
+    F32,
+}
+
+/// Describes the audio carried by the stream that follows it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamHeader {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub sample_format: SampleFormat,
+    pub codec: Codec,
+}
+
+/// Write a length-prefixed frame. Flushes once after both the length
+/// prefix and the payload so a buffering `Writer` sends them as a single
+/// batch instead of two small writes.
+pub fn write_frame<W: Write>(writer: &mut W, bytes: &[u8]) -> Res<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one length-prefixed frame, or `None` if the peer closed the
+/// connection cleanly before sending one.
+pub fn read_frame<R: Read>(reader: &mut R) -> Res<Option<Vec<u8>>> {
+    let mut len_buf = [0u8; 4];
+    if let Err(err) = reader.read_exact(&mut len_buf) {
+        if err.kind() == ErrorKind::UnexpectedEof {
+            return Ok(None);
+        }
+        return Err(err.into());
+    }
+    let len = u32::from_le_bytes(len_buf) as usize;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// Serialize and send the handshake header as the first frame on the stream.
+pub fn write_header<W: Write>(writer: &mut W, header: &StreamHeader) -> Res<()> {
+    let bytes = rmp_serde::to_vec(header)?;
+    write_frame(writer, &bytes)
+}
+
+/// Read and deserialize the handshake header, which must be the first
+/// frame a client sends.
+pub fn read_header<R: Read>(reader: &mut R) -> Res<StreamHeader> {
+    let bytes = read_frame(reader)?.ok_or("connection closed before sending stream header")?;
+    Ok(rmp_serde::from_slice(&bytes)?)
+}
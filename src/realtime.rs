@@ -0,0 +1,134 @@
+//! Opt-in real-time scheduling for the latency-sensitive capture and
+//! playback threads.
+//!
+//! This only ever raises priority for the lifetime of a [`Guard`]; dropping
+//! it restores whatever scheduling policy the thread had before, so a
+//! thread that exits (cleanly or via an early return) never leaves a
+//! runaway real-time thread behind.
+
+use tracing::warn;
+
+/// Bounds how aggressive we're willing to get with `SCHED_RR` on Linux.
+/// Staying well under the platform max leaves room for the kernel's own
+/// housekeeping threads, avoiding a runaway real-time thread starving the
+/// rest of the system.
+#[cfg(target_os = "linux")]
+const MAX_RR_PRIORITY_FRACTION: i32 = 2;
+
+/// Restores the previous scheduling policy/priority on drop.
+pub struct Guard {
+    #[cfg(target_os = "linux")]
+    previous: Option<(libc::c_int, libc::sched_param)>,
+    #[cfg(windows)]
+    mmcss_handle: Option<windows::Win32::Foundation::HANDLE>,
+}
+
+/// Best-effort: raise the calling thread to real-time priority. Falls back
+/// to the default policy (with a logged warning) if the OS refuses, which
+/// is expected when the process lacks the relevant permission (Linux:
+/// `CAP_SYS_NICE` / an `RLIMIT_RTPRIO`; Windows: none needed, but MMCSS can
+/// still refuse).
+pub fn enable(label: &str) -> Guard {
+    #[cfg(target_os = "linux")]
+    {
+        enable_linux(label)
+    }
+    #[cfg(windows)]
+    {
+        enable_windows(label)
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    {
+        warn!(
+            "realtime scheduling is not implemented on this platform, running \"{}\" at default priority",
+            label
+        );
+        Guard {}
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn enable_linux(label: &str) -> Guard {
+    use std::mem::MaybeUninit;
+
+    // Remember the current policy/priority so we can restore it on drop.
+    let tid = 0; // 0 means "calling thread" for the sched_* family.
+    let previous_policy = unsafe { libc::sched_getscheduler(tid) };
+    let mut previous_param = unsafe { MaybeUninit::<libc::sched_param>::zeroed().assume_init() };
+    if previous_policy < 0 || unsafe { libc::sched_getparam(tid, &mut previous_param) } != 0 {
+        warn!(
+            "could not read current scheduling policy for \"{}\", skipping realtime priority",
+            label
+        );
+        return Guard { previous: None };
+    }
+
+    let max_priority = unsafe { libc::sched_get_priority_max(libc::SCHED_RR) };
+    let min_priority = unsafe { libc::sched_get_priority_min(libc::SCHED_RR) };
+    if max_priority < 0 || min_priority < 0 {
+        warn!("SCHED_RR is not supported on this system, skipping realtime priority");
+        return Guard { previous: None };
+    }
+    let target_priority = min_priority + (max_priority - min_priority) / MAX_RR_PRIORITY_FRACTION;
+
+    let mut rr_param = unsafe { MaybeUninit::<libc::sched_param>::zeroed().assume_init() };
+    rr_param.sched_priority = target_priority;
+    let result = unsafe { libc::sched_setscheduler(tid, libc::SCHED_RR, &rr_param) };
+    if result != 0 {
+        warn!(
+            "failed to raise \"{}\" to SCHED_RR priority {} (permission denied?), running at default priority",
+            label, target_priority
+        );
+        return Guard { previous: None };
+    }
+
+    Guard {
+        previous: Some((previous_policy, previous_param)),
+    }
+}
+
+#[cfg(windows)]
+fn enable_windows(label: &str) -> Guard {
+    use windows::core::PCWSTR;
+    use windows::Win32::Media::Audio::AvSetMmThreadCharacteristicsW;
+
+    // "Pro Audio" is the MMCSS task category meant for exactly this kind of
+    // latency-sensitive capture/render thread.
+    let mut task_name: Vec<u16> = "Pro Audio\0".encode_utf16().collect();
+    let mut task_index: u32 = 0;
+    let handle = unsafe {
+        AvSetMmThreadCharacteristicsW(PCWSTR(task_name.as_mut_ptr()), &mut task_index)
+    };
+
+    match handle {
+        Ok(handle) => Guard {
+            mmcss_handle: Some(handle),
+        },
+        Err(err) => {
+            warn!(
+                "failed to register \"{}\" with MMCSS ({}), running at default priority",
+                label, err
+            );
+            Guard {
+                mmcss_handle: None,
+            }
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some((policy, param)) = self.previous {
+            let result = unsafe { libc::sched_setscheduler(0, policy, &param) };
+            if result != 0 {
+                warn!("failed to restore previous scheduling policy after realtime capture/playback");
+            }
+        }
+        #[cfg(windows)]
+        if let Some(handle) = self.mmcss_handle.take() {
+            use windows::Win32::Media::Audio::AvRevertMmThreadCharacteristics;
+            let _ = unsafe { AvRevertMmThreadCharacteristics(handle) };
+        }
+    }
+}
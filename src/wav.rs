@@ -0,0 +1,209 @@
+//! Minimal RIFF/WAVE reading and writing.
+//!
+//! Just enough to (a) write a real, playable header around the raw f32 PCM
+//! this project already captures, and (b) parse a `fmt ` chunk out of an
+//! existing WAV file so it can be pushed through the same playback buffer
+//! as a live network source.
+
+use crate::Res;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// Format parsed out of a WAV file's `fmt ` chunk.
+#[derive(Debug, Clone, Copy)]
+pub struct WavFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+/// Writes a RIFF/WAVE header up front with placeholder sizes, then
+/// back-patches the RIFF and `data` chunk sizes once the real byte count
+/// is known.
+pub struct WavWriter {
+    file: File,
+    data_bytes: u32,
+}
+
+impl WavWriter {
+    /// Create `path` and write a WAV header for `channels` of 32-bit float
+    /// PCM at `sample_rate`, ready for `data` to follow.
+    pub fn create(path: &str, sample_rate: u32, channels: u16) -> Res<Self> {
+        let mut file = File::create(path)?;
+
+        let bits_per_sample: u16 = 32;
+        let block_align = channels * (bits_per_sample / 8);
+        let byte_rate = sample_rate * block_align as u32;
+
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?; // RIFF size, patched in on finalize
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        file.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&byte_rate.to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&bits_per_sample.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?; // data size, patched in on finalize
+
+        Ok(WavWriter {
+            file,
+            data_bytes: 0,
+        })
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> Res<()> {
+        self.file.write_all(bytes)?;
+        self.data_bytes += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Back-patch the RIFF and `data` chunk sizes now that the real byte
+    /// count is known.
+    fn finalize(&mut self) -> Res<()> {
+        let riff_size = 4 + (8 + 16) + (8 + self.data_bytes);
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&riff_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_bytes.to_le_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.finalize() {
+            tracing::error!("failed to finalize WAV header: {}", err);
+        }
+    }
+}
+
+/// Read a WAV file's `fmt ` and `data` chunks. Walks chunks generically so
+/// chunk order (and the odd extra chunk some encoders add) doesn't matter,
+/// stopping once both have been found.
+pub fn read_wav_pcm(path: &str) -> Res<(WavFormat, Vec<u8>)> {
+    let mut file = File::open(path)?;
+
+    let mut riff_header = [0u8; 12];
+    file.read_exact(&mut riff_header)?;
+    if &riff_header[0..4] != b"RIFF" || &riff_header[8..12] != b"WAVE" {
+        return Err("not a RIFF/WAVE file".into());
+    }
+
+    let mut format: Option<WavFormat> = None;
+    let mut data: Option<Vec<u8>> = None;
+
+    while format.is_none() || data.is_none() {
+        let mut chunk_header = [0u8; 8];
+        if file.read_exact(&mut chunk_header).is_err() {
+            break;
+        }
+        let chunk_id = &chunk_header[0..4];
+        let chunk_size = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap()) as usize;
+
+        if chunk_id == b"fmt " {
+            let mut fmt_body = vec![0u8; chunk_size];
+            file.read_exact(&mut fmt_body)?;
+            format = Some(WavFormat {
+                channels: u16::from_le_bytes(fmt_body[2..4].try_into().unwrap()),
+                sample_rate: u32::from_le_bytes(fmt_body[4..8].try_into().unwrap()),
+                bits_per_sample: u16::from_le_bytes(fmt_body[14..16].try_into().unwrap()),
+            });
+        } else if chunk_id == b"data" {
+            let mut chunk_data = vec![0u8; chunk_size];
+            file.read_exact(&mut chunk_data)?;
+            data = Some(chunk_data);
+        } else {
+            file.seek(SeekFrom::Current(chunk_size as i64))?;
+        }
+
+        // Chunks are word-aligned; skip the pad byte for odd-sized chunks.
+        if chunk_size % 2 == 1 {
+            file.seek(SeekFrom::Current(1))?;
+        }
+    }
+
+    let format = format.ok_or("WAV file has no fmt chunk")?;
+    let data = data.ok_or("WAV file has no data chunk")?;
+    Ok((format, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Each test gets its own path under the system temp dir so parallel
+    /// test runs don't stomp on each other's files.
+    fn temp_wav_path(label: &str) -> String {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir()
+            .join(format!("audio_wire_test_{label}_{n}.wav"))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn write_then_read_round_trip() {
+        let path = temp_wav_path("round_trip");
+        let samples: Vec<f32> = vec![0.0, 0.25, -0.25, 1.0, -1.0, 0.5];
+        let pcm_bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+        {
+            let mut writer = WavWriter::create(&path, 48_000, 2).unwrap();
+            writer.write_all(&pcm_bytes).unwrap();
+            // Drop here finalizes the header (back-patches RIFF/data sizes).
+        }
+
+        let (format, data) = read_wav_pcm(&path).unwrap();
+        assert_eq!(format.sample_rate, 48_000);
+        assert_eq!(format.channels, 2);
+        assert_eq!(format.bits_per_sample, 32);
+        assert_eq!(data, pcm_bytes);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn backpatched_sizes_match_file_length() {
+        let path = temp_wav_path("sizes");
+        let pcm_bytes = vec![0u8; 4 * 10]; // 10 f32 samples
+
+        {
+            let mut writer = WavWriter::create(&path, 44_100, 1).unwrap();
+            writer.write_all(&pcm_bytes).unwrap();
+        }
+
+        let file_len = std::fs::metadata(&path).unwrap().len() as u32;
+        let mut file = File::open(&path).unwrap();
+        let mut header = [0u8; 44];
+        file.read_exact(&mut header).unwrap();
+
+        let riff_size = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let data_size = u32::from_le_bytes(header[40..44].try_into().unwrap());
+
+        assert_eq!(riff_size, file_len - 8);
+        assert_eq!(data_size, pcm_bytes.len() as u32);
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn rejects_non_riff_file() {
+        let path = temp_wav_path("not_riff");
+        std::fs::write(&path, b"not a wav file at all").unwrap();
+
+        assert!(read_wav_pcm(&path).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+}